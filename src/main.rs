@@ -1,18 +1,32 @@
 extern crate termion;
 extern crate clap;
+extern crate regex;
+extern crate ropey;
 
 use clap::{App, Arg};
+use regex::Regex;
+use ropey::Rope;
 use std::io::{self, stdin, stdout, Write};
 use std::path;
 use std::ffi::OsStr;
 use termion::clear;
+use termion::color;
 use termion::cursor;
 use termion::event::{Event, Key};
 use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 use termion::screen::AlternateScreen;
+use termion::style;
 use std::fs;
 use std::cmp::{min, max};
+use std::time::{Duration, Instant};
+
+// メッセージラインに表示するメッセージが消えるまでの時間
+const STATUS_MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);
+// タブ1つが占める桁数
+const TAB_STOP: usize = 4;
+// 未保存のまま終了するために必要なCtrl-Cの連続押下回数
+const QUIT_CONFIRM_COUNT: usize = 3;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 struct Cursor {
@@ -20,20 +34,51 @@ struct Cursor {
 	column: usize,
 }
 
+// 取り消し/やり直しできる編集操作。cursor_beforeは操作前のカーソル位置。
+#[derive(Debug, Clone)]
+enum Edit {
+	InsertChar { cursor_before: Cursor, c: char },
+	DeleteChar { cursor_before: Cursor, c: char },
+	SplitLine { cursor_before: Cursor },
+	JoinLine { cursor_before: Cursor, join_idx: usize },
+	InsertText { cursor_before: Cursor, text: String, end: Cursor },
+	DeleteRange { start: Cursor, end: Cursor, text: String },
+}
+
 struct Kiro {
-	buffer: Vec<Vec<char>>,
+	buffer: Rope,
 	cursor: Cursor,
 	row_offset: usize,
 	path: Option<path::PathBuf>,
+	status_message: String,
+	status_message_time: Instant,
+	undo_stack: Vec<Vec<Edit>>,
+	redo_stack: Vec<Vec<Edit>>,
+	current_group: Vec<Edit>,
+	marker: Option<Cursor>,
+	clipboard: String,
+	search_highlight: Option<(Cursor, Cursor)>,
+	dirty: bool,
+	quit_confirm_count: usize,
 }
 
 impl Default for Kiro {
 	fn default() -> Self {
 		Self {
-			buffer: vec![Vec::new()],
+			buffer: Rope::new(),
 			cursor: Cursor { row: 0, column: 0 },
 			row_offset: 0,
 			path: None,
+			status_message: String::new(),
+			status_message_time: Instant::now(),
+			undo_stack: Vec::new(),
+			redo_stack: Vec::new(),
+			current_group: Vec::new(),
+			marker: None,
+			clipboard: String::new(),
+			search_highlight: None,
+			dirty: false,
+			quit_confirm_count: 0,
 		}
 	}
 }
@@ -46,27 +91,96 @@ impl Kiro {
 	}
 	// ファイルを読み込む
 	fn open(&mut self, path: &path::Path) {
-		self.buffer = fs::read_to_string(path)
+		let normalized = fs::read_to_string(path)
 			.ok()
 			.map(|s| {
-				let buffer: Vec<Vec<char>> = s
-					.lines()
-					.map(|line| line.trim_end().chars().collect())
-					.collect();
-				if buffer.is_empty() {
-					vec![Vec::new()]
-				} else {
-					buffer
-				}
+				s.lines()
+					.map(|line| line.trim_end())
+					.collect::<Vec<_>>()
+					.join("\n")
 			})
-			.unwrap_or_else(|| vec![Vec::new()]);
+			.unwrap_or_default();
+		self.buffer = Rope::from_str(&normalized);
 		self.path = Some(path.into());
-		self.cursor = Cursor {row: 0, column: 0};
+		self.cursor = Cursor { row: 0, column: 0 };
 		self.row_offset = 0;
 	}
-	// 画面描写
+	// 論理行数(バッファ内の改行は行区切りなので、末尾の改行の後の空行も1行として数える)
+	fn len_lines(&self) -> usize {
+		self.buffer.len_lines()
+	}
+	// row行目の内容を改行コードを含めずVec<char>として取り出す
+	fn line_chars(&self, row: usize) -> Vec<char> {
+		let mut chars: Vec<char> = self.buffer.line(row).chars().collect();
+		if chars.last() == Some(&'\n') {
+			chars.pop();
+		}
+		if chars.last() == Some(&'\r') {
+			chars.pop();
+		}
+		chars
+	}
+	// row行目の改行コードを含まない文字数
+	fn line_len(&self, row: usize) -> usize {
+		self.line_chars(row).len()
+	}
+	// カーソル位置をrope全体でのchar位置に変換する
+	fn char_idx(&self, cursor: Cursor) -> usize {
+		self.buffer.line_to_char(cursor.row) + cursor.column
+	}
+	// rope全体でのchar位置をカーソル位置に変換する
+	fn idx_to_cursor(&self, idx: usize) -> Cursor {
+		let row = self.buffer.char_to_line(idx);
+		let column = idx - self.buffer.line_to_char(row);
+		Cursor { row, column }
+	}
+	// カーソルがバッファの範囲内に収まるように行・列をクランプする
+	fn clamp_cursor(&self, cursor: Cursor) -> Cursor {
+		let row = min(cursor.row, self.len_lines() - 1);
+		let column = min(cursor.column, self.line_len(row));
+		Cursor { row, column }
+	}
+	// 文字を空白・単語構成文字(英数字/アンダースコア)・記号のいずれかに分類する
+	fn word_class(c: char) -> u8 {
+		if c.is_whitespace() {
+			0
+		} else if c.is_alphanumeric() || c == '_' {
+			1
+		} else {
+			2
+		}
+	}
+	// タブをTAB_STOP桁境界まで空白展開した描写用の行を作る
+	fn render_row(row: &[char]) -> Vec<char> {
+		let mut rendered = Vec::with_capacity(row.len());
+		for &c in row {
+			if c == '\t' {
+				rendered.push(' ');
+				while rendered.len() % TAB_STOP != 0 {
+					rendered.push(' ');
+				}
+			} else {
+				rendered.push(c);
+			}
+		}
+		rendered
+	}
+	// 論理カーソル列を描写上の桁に変換する(タブ展開を考慮)
+	fn cursor_x_to_render_x(row: &[char], cursor_x: usize) -> usize {
+		let mut render_x = 0;
+		for &c in &row[..cursor_x] {
+			if c == '\t' {
+				render_x += TAB_STOP - (render_x % TAB_STOP);
+			} else {
+				render_x += 1;
+			}
+		}
+		render_x
+	}
+	// 画面描写。可視範囲(row_offset..row_offset+text_rows)の行だけをropeから取り出して描く
 	fn draw<T: Write>(&self, out: &mut T) {
 		let (rows, cols) = Self::terminal_size();
+		let text_rows = rows.saturating_sub(2);
 
 		write!(out, "{}", clear::All);
 		write!(out, "{}", cursor::Goto(1, 1));
@@ -76,23 +190,53 @@ impl Kiro {
 		let mut col = 0;
 
 		let mut cursor: Option<Cursor> = None;
+		let selection = self.search_highlight.or_else(|| self.selection_range());
 
-		'outer: for i in self.row_offset..self.buffer.len() {
-			for j in 0..=self.buffer[i].len() {
-				if self.cursor == (Cursor { row: i, column: j }) {
+		'outer: for i in self.row_offset..self.len_lines() {
+			let line = self.line_chars(i);
+			let rendered = Self::render_row(&line);
+			let cursor_render_x = if self.cursor.row == i {
+				Self::cursor_x_to_render_x(&line, self.cursor.column)
+			} else {
+				0
+			};
+			// この行の選択範囲を描写桁の範囲に変換しておく
+			let row_selection = selection.filter(|(start, end)| i >= start.row && i <= end.row).map(
+				|(start, end)| {
+					let from = if i == start.row {
+						Self::cursor_x_to_render_x(&line, start.column)
+					} else {
+						0
+					};
+					let to = if i == end.row {
+						Self::cursor_x_to_render_x(&line, end.column)
+					} else {
+						rendered.len()
+					};
+					(from, to)
+				},
+			);
+
+			for j in 0..=rendered.len() {
+				if self.cursor.row == i && cursor_render_x == j {
 					cursor = Some(Cursor {
 						row: row,
 						column: col,
 					});
 				}
 
-				if let Some(c) = self.buffer[i].get(j) {
-					write!(out, "{}", c);
+				if let Some(c) = rendered.get(j) {
+					let highlighted = row_selection.map_or(false, |(from, to)| j >= from && j < to);
+					if highlighted {
+						write!(out, "{}{}{}", style::Invert, c, style::Reset);
+					} else {
+						write!(out, "{}", c);
+					}
 					col += 1;
 					if col >= cols {
 						row += 1;
 						col = 0;
-						if row >= rows {
+						if row >= text_rows {
 							break 'outer;
 						} else {
 							write!(out, "\r\n");
@@ -102,43 +246,395 @@ impl Kiro {
 			}
 			row += 1;
 			col = 0;
-			if row >= rows {
+			if row >= text_rows {
 				break;
 			} else {
 				write!(out, "\r\n");
 			}
 		}
 
+		while row < text_rows {
+			write!(out, "\r\n");
+			row += 1;
+		}
+
+		self.draw_status_bar(out, cols);
+		self.draw_message_line(out, cols);
+
 		if let Some(cursor) = cursor {
 			write!(
 				out,
-				"{}", 
+				"{}",
 				cursor::Goto(cursor.column as u16 + 1, cursor.row as u16 + 1)
 			);
 		}
 
 		out.flush().unwrap();
 	}
+	// ステータスバー(ファイル名、行数、カーソル位置)を反転表示で描写
+	fn draw_status_bar<T: Write>(&self, out: &mut T, cols: usize) {
+		let filename = self
+			.path
+			.as_ref()
+			.and_then(|p| p.file_name())
+			.and_then(|n| n.to_str())
+			.unwrap_or("[No Name]");
+		let left = format!(
+			"{} - {} lines {}",
+			filename,
+			self.len_lines(),
+			if self.dirty { "(modified)" } else { "" }
+		);
+		let right = format!("{}/{}", self.cursor.row + 1, self.cursor.column + 1);
+
+		let left_len = left.chars().count();
+		let right_len = right.chars().count();
+		let mut status = left;
+		if left_len + right_len < cols {
+			status.push_str(&" ".repeat(cols - left_len - right_len));
+		}
+		status.push_str(&right);
+		status = status.chars().take(cols).collect();
+
+		write!(
+			out,
+			"\r\n{}{}{}{}",
+			style::Invert,
+			status,
+			style::Reset,
+			color::Fg(color::Reset)
+		);
+	}
+	// メッセージライン(一定時間で消える通知)を描写
+	fn draw_message_line<T: Write>(&self, out: &mut T, cols: usize) {
+		write!(out, "\r\n");
+		if self.status_message_time.elapsed() < STATUS_MESSAGE_TIMEOUT {
+			let mut message = self.status_message.clone();
+			message = message.chars().take(cols).collect();
+			write!(out, "{}", message);
+		}
+	}
 	// カーソルが画面に映るようにrow_offsetを設定
 	fn scroll(&mut self) {
 		let (rows, _) = Self::terminal_size();
+		let text_rows = rows.saturating_sub(2);
 		self.row_offset = min(self.row_offset, self.cursor.row);
-		if self.cursor.row + 1 >= rows { 
-			self.row_offset = max(self.row_offset, self.cursor.row + 1 - rows);
+		if self.cursor.row + 1 >= text_rows {
+			self.row_offset = max(self.row_offset, self.cursor.row + 1 - text_rows);
+		}
+	}
+	// メッセージラインに通知を表示する
+	fn set_status_message(&mut self, message: String) {
+		self.status_message = message;
+		self.status_message_time = Instant::now();
+	}
+	// 終了キー以外が押されたら連続押下カウントをリセットする
+	fn reset_quit_confirm(&mut self) {
+		self.quit_confirm_count = 0;
+	}
+	// 終了を要求する。未保存の変更がなければ即座にtrueを返し、あればQUIT_CONFIRM_COUNT回連続で
+	// 呼ばれるまでfalseを返し警告を表示する
+	fn request_quit(&mut self) -> bool {
+		if !self.dirty {
+			return true;
+		}
+		self.quit_confirm_count += 1;
+		if self.quit_confirm_count >= QUIT_CONFIRM_COUNT {
+			true
+		} else {
+			self.set_status_message(format!(
+				"WARNING!!! File has unsaved changes. Press Ctrl-C {} more time(s) to quit.",
+				QUIT_CONFIRM_COUNT - self.quit_confirm_count
+			));
+			false
+		}
+	}
+	// 編集操作を現在のグループに積む。連続する同一行の文字挿入はグループ化し、それ以外は新しいグループを開始する
+	fn push_edit(&mut self, edit: Edit) {
+		let coalesces = match (self.current_group.last(), &edit) {
+			(
+				Some(Edit::InsertChar { cursor_before: prev, .. }),
+				Edit::InsertChar { cursor_before: next, .. },
+			) => prev.row == next.row && prev.column + 1 == next.column,
+			_ => false,
+		};
+		if !coalesces {
+			self.commit_group();
+		}
+		self.dirty = true;
+		self.current_group.push(edit);
+		self.redo_stack.clear();
+	}
+	// 進行中の編集グループをundoスタックに確定する
+	fn commit_group(&mut self) {
+		if !self.current_group.is_empty() {
+			self.undo_stack.push(std::mem::take(&mut self.current_group));
+		}
+	}
+	// 直前の編集操作を取り消す
+	fn undo(&mut self) {
+		self.commit_group();
+		if let Some(group) = self.undo_stack.pop() {
+			for edit in group.iter().rev() {
+				self.apply_inverse(edit);
+			}
+			self.redo_stack.push(group);
+		}
+	}
+	// 取り消した編集操作をやり直す
+	fn redo(&mut self) {
+		if let Some(group) = self.redo_stack.pop() {
+			for edit in group.iter() {
+				self.apply_forward(edit);
+			}
+			self.undo_stack.push(group);
+		}
+	}
+	// 編集操作の逆操作を適用する
+	fn apply_inverse(&mut self, edit: &Edit) {
+		self.dirty = true;
+		match edit {
+			Edit::InsertChar { cursor_before, .. } => {
+				let idx = self.char_idx(*cursor_before);
+				self.buffer.remove(idx..idx + 1);
+				self.cursor = *cursor_before;
+			}
+			Edit::DeleteChar { cursor_before, c } => {
+				let idx = self.char_idx(*cursor_before) - 1;
+				self.buffer.insert_char(idx, *c);
+				self.cursor = *cursor_before;
+			}
+			Edit::SplitLine { cursor_before } => {
+				let idx = self.char_idx(*cursor_before);
+				self.buffer.remove(idx..idx + 1);
+				self.cursor = *cursor_before;
+			}
+			Edit::JoinLine { cursor_before, join_idx } => {
+				self.buffer.insert_char(*join_idx, '\n');
+				self.cursor = *cursor_before;
+			}
+			Edit::InsertText { cursor_before, end, .. } => {
+				self.raw_delete_range(*cursor_before, *end);
+				self.cursor = *cursor_before;
+			}
+			Edit::DeleteRange { start, text, .. } => {
+				self.raw_insert_text(*start, text);
+				self.cursor = *start;
+			}
+		}
+	}
+	// 編集操作を再適用する
+	fn apply_forward(&mut self, edit: &Edit) {
+		self.dirty = true;
+		match edit {
+			Edit::InsertChar { cursor_before, c } => {
+				let idx = self.char_idx(*cursor_before);
+				self.buffer.insert_char(idx, *c);
+				self.cursor = Cursor { row: cursor_before.row, column: cursor_before.column + 1 };
+			}
+			Edit::DeleteChar { cursor_before, .. } => {
+				let idx = self.char_idx(*cursor_before) - 1;
+				self.buffer.remove(idx..idx + 1);
+				self.cursor = Cursor { row: cursor_before.row, column: cursor_before.column - 1 };
+			}
+			Edit::SplitLine { cursor_before } => {
+				let idx = self.char_idx(*cursor_before);
+				self.buffer.insert_char(idx, '\n');
+				self.cursor = Cursor { row: cursor_before.row + 1, column: 0 };
+			}
+			Edit::JoinLine { cursor_before, join_idx } => {
+				let prev_len = self.line_len(cursor_before.row - 1);
+				self.buffer.remove(*join_idx..*join_idx + 1);
+				self.cursor = Cursor { row: cursor_before.row - 1, column: prev_len };
+			}
+			Edit::InsertText { cursor_before, text, .. } => {
+				self.cursor = self.raw_insert_text(*cursor_before, text);
+			}
+			Edit::DeleteRange { start, end, .. } => {
+				self.raw_delete_range(*start, *end);
+				self.cursor = *start;
+			}
+		}
+	}
+	// 選択範囲の開始/解除を切り替える
+	fn toggle_marker(&mut self) {
+		self.marker = if self.marker.is_some() { None } else { Some(self.cursor) };
+	}
+	// マーカーとカーソルから正規化された選択範囲(開始 <= 終了、終了は範囲外)を求める
+	fn selection_range(&self) -> Option<(Cursor, Cursor)> {
+		let marker = self.marker?;
+		let (start, end) = if (marker.row, marker.column) <= (self.cursor.row, self.cursor.column) {
+			(marker, self.cursor)
+		} else {
+			(self.cursor, marker)
+		};
+		if start == end {
+			None
+		} else {
+			Some((start, end))
+		}
+	}
+	// [start, end)の範囲の文字列を読み取る(バッファは変更しない)
+	fn text_in_range(&self, start: Cursor, end: Cursor) -> String {
+		let start_idx = self.char_idx(start);
+		let end_idx = self.char_idx(end);
+		self.buffer.slice(start_idx..end_idx).to_string()
+	}
+	// [start, end)の範囲を削除し、削除した文字列を返す
+	fn raw_delete_range(&mut self, start: Cursor, end: Cursor) -> String {
+		let start_idx = self.char_idx(start);
+		let end_idx = self.char_idx(end);
+		let text = self.buffer.slice(start_idx..end_idx).to_string();
+		self.buffer.remove(start_idx..end_idx);
+		text
+	}
+	// atの位置にtextを挿入し、挿入し終わった直後のカーソル位置を返す
+	fn raw_insert_text(&mut self, at: Cursor, text: &str) -> Cursor {
+		let start_idx = self.char_idx(at);
+		self.buffer.insert(start_idx, text);
+		let end_idx = start_idx + text.chars().count();
+		let end_row = self.buffer.char_to_line(end_idx);
+		let end_column = end_idx - self.buffer.line_to_char(end_row);
+		Cursor { row: end_row, column: end_column }
+	}
+	// 選択範囲をクリップボードにコピーする
+	fn copy_selection(&mut self) {
+		if let Some((start, end)) = self.selection_range() {
+			self.clipboard = self.text_in_range(start, end);
+		}
+	}
+	// 選択範囲をクリップボードに切り取る
+	fn cut_selection(&mut self) {
+		if let Some((start, end)) = self.selection_range() {
+			let text = self.raw_delete_range(start, end);
+			self.cursor = start;
+			self.marker = None;
+			self.clipboard = text.clone();
+			self.push_edit(Edit::DeleteRange { start, end, text });
+		}
+	}
+	// クリップボードの内容をカーソル位置に貼り付ける
+	fn paste(&mut self) {
+		if self.clipboard.is_empty() {
+			return;
+		}
+		let cursor_before = self.cursor;
+		let text = self.clipboard.clone();
+		let end = self.raw_insert_text(cursor_before, &text);
+		self.cursor = end;
+		self.push_edit(Edit::InsertText { cursor_before, text, end });
+	}
+	// インクリメンタル検索モード。クエリを1文字ずつ読み取りながら次/前のマッチへカーソルを移動する
+	fn search<I, T>(&mut self, events: &mut I, out: &mut T)
+	where
+		I: Iterator<Item = io::Result<Event>>,
+		T: Write,
+	{
+		let saved_cursor = self.cursor;
+		let saved_row_offset = self.row_offset;
+		let mut query = String::new();
+
+		loop {
+			self.set_status_message(format!("Search (Esc to cancel, Enter to confirm): {}", query));
+			self.draw(out);
+
+			match events.next() {
+				Some(Ok(Event::Key(Key::Esc))) => {
+					self.cursor = saved_cursor;
+					self.row_offset = saved_row_offset;
+					break;
+				}
+				Some(Ok(Event::Key(Key::Char('\n')))) => {
+					break;
+				}
+				Some(Ok(Event::Key(Key::Backspace))) => {
+					query.pop();
+					self.find_match(&query, true);
+				}
+				Some(Ok(Event::Key(Key::Right))) | Some(Ok(Event::Key(Key::Down))) => {
+					self.find_match(&query, true);
+				}
+				Some(Ok(Event::Key(Key::Left))) | Some(Ok(Event::Key(Key::Up))) => {
+					self.find_match(&query, false);
+				}
+				Some(Ok(Event::Key(Key::Char(c)))) => {
+					query.push(c);
+					self.find_match(&query, true);
+				}
+				Some(Ok(_)) => {}
+				Some(Err(_)) | None => break,
+			}
+		}
+
+		self.search_highlight = None;
+		self.set_status_message(String::new());
+	}
+	// queryにマッチする次(forward)または前の箇所へカーソルを移動し、その箇所をハイライトする
+	fn find_match(&mut self, query: &str, forward: bool) {
+		self.search_highlight = None;
+		if query.is_empty() {
+			return;
+		}
+		let re = match Regex::new(query) {
+			Ok(re) => re,
+			Err(_) => return,
+		};
+		let n = self.len_lines();
+		if n == 0 {
+			return;
+		}
+
+		for offset in 0..=n {
+			let row = if forward {
+				(self.cursor.row + offset) % n
+			} else {
+				(self.cursor.row + n - offset % n) % n
+			};
+			let line: String = self.line_chars(row).into_iter().collect();
+			let matches: Vec<_> = re.find_iter(&line).collect();
+			if matches.is_empty() {
+				continue;
+			}
+
+			let found = if offset == 0 {
+				if forward {
+					matches
+						.iter()
+						.find(|m| line[..m.start()].chars().count() > self.cursor.column)
+				} else {
+					matches
+						.iter()
+						.rev()
+						.find(|m| line[..m.start()].chars().count() < self.cursor.column)
+				}
+			} else if forward {
+				matches.first()
+			} else {
+				matches.last()
+			};
+
+			if let Some(m) = found {
+				let start = Cursor { row, column: line[..m.start()].chars().count() };
+				let end = Cursor { row, column: line[..m.end()].chars().count() };
+				self.cursor = start;
+				self.scroll();
+				self.search_highlight = Some((start, end));
+				return;
+			}
 		}
 	}
 	// カーソルUP
 	fn cursor_up(&mut self) {
 		if self.cursor.row > 0 {
 			self.cursor.row -= 1;
-			self.cursor.column = min(self.buffer[self.cursor.row].len(), self.cursor.column);
+			self.cursor.column = min(self.line_len(self.cursor.row), self.cursor.column);
 		}
 	}
 	// カーソルDOWN
 	fn cursor_down(&mut self) {
-		if self.cursor.row + 1 < self.buffer.len() {
+		if self.cursor.row + 1 < self.len_lines() {
 			self.cursor.row += 1;
-			self.cursor.column = min(self.cursor.column, self.buffer[self.cursor.row].len());
+			self.cursor.column = min(self.cursor.column, self.line_len(self.cursor.row));
 		}
 	}
 	// カーソルLEFT
@@ -149,45 +645,103 @@ impl Kiro {
 	}
 	// カーソルRIGHT
 	fn cursor_right(&mut self) {
-		self.cursor.column = min(self.cursor.column + 1, self.buffer[self.cursor.row].len());
+		self.cursor.column = min(self.cursor.column + 1, self.line_len(self.cursor.row));
+	}
+	// 次の単語の先頭へカーソルを進める。行末に達した場合は次の行へ回り込む
+	fn move_next_word_start(&mut self) {
+		let len = self.buffer.len_chars();
+		let mut idx = self.char_idx(self.cursor);
+		if idx < len && Self::word_class(self.buffer.char(idx)) != 0 {
+			let class = Self::word_class(self.buffer.char(idx));
+			while idx < len && Self::word_class(self.buffer.char(idx)) == class {
+				idx += 1;
+			}
+		}
+		while idx < len && Self::word_class(self.buffer.char(idx)) == 0 {
+			idx += 1;
+		}
+		self.cursor = self.clamp_cursor(self.idx_to_cursor(idx));
+	}
+	// 前の単語の先頭へカーソルを戻す。行頭に達した場合は前の行へ回り込む
+	fn move_prev_word_start(&mut self) {
+		let mut idx = self.char_idx(self.cursor);
+		if idx == 0 {
+			return;
+		}
+		idx -= 1;
+		while idx > 0 && Self::word_class(self.buffer.char(idx)) == 0 {
+			idx -= 1;
+		}
+		if Self::word_class(self.buffer.char(idx)) != 0 {
+			let class = Self::word_class(self.buffer.char(idx));
+			while idx > 0 && Self::word_class(self.buffer.char(idx - 1)) == class {
+				idx -= 1;
+			}
+		}
+		self.cursor = self.clamp_cursor(self.idx_to_cursor(idx));
+	}
+	// 現在の単語、または次の単語の末尾へカーソルを進める
+	fn move_next_word_end(&mut self) {
+		let len = self.buffer.len_chars();
+		let mut idx = self.char_idx(self.cursor);
+		if idx + 1 >= len {
+			return;
+		}
+		idx += 1;
+		while idx < len && Self::word_class(self.buffer.char(idx)) == 0 {
+			idx += 1;
+		}
+		if idx < len {
+			let class = Self::word_class(self.buffer.char(idx));
+			while idx + 1 < len && Self::word_class(self.buffer.char(idx + 1)) == class {
+				idx += 1;
+			}
+		}
+		self.cursor = self.clamp_cursor(self.idx_to_cursor(min(idx, len - 1)));
 	}
 	// 文字入力
 	fn insert(&mut self, c: char) {
+		let cursor_before = self.cursor;
 		if c == '\n' {
-			let rest: Vec<char> = self.buffer[self.cursor.row].drain(self.cursor.column..).collect();
-			self.buffer.insert(self.cursor.row + 1, rest);
-			self.cursor.row += 1;
-			self.cursor.column = 0;
-		} else if !c.is_control() {
-			self.buffer[self.cursor.row].insert(self.cursor.column, c);
+			let idx = self.char_idx(cursor_before);
+			self.buffer.insert_char(idx, '\n');
+			self.cursor = Cursor { row: cursor_before.row + 1, column: 0 };
+			self.push_edit(Edit::SplitLine { cursor_before });
+		} else if c == '\t' || !c.is_control() {
+			let idx = self.char_idx(cursor_before);
+			self.buffer.insert_char(idx, c);
 			self.cursor_right();
+			self.push_edit(Edit::InsertChar { cursor_before, c });
 		}
 	}
 	// 文字消去
 	fn delete(&mut self) {
-		if self.cursor.column > 0 {
-			let mut later = self.buffer[self.cursor.row].split_off(self.cursor.column);
-			self.buffer[self.cursor.row].pop();
-			self.buffer[self.cursor.row].append(&mut later);
+		let cursor_before = self.cursor;
+		if cursor_before.column > 0 {
+			let idx = self.char_idx(cursor_before) - 1;
+			let c = self.buffer.char(idx);
+			self.buffer.remove(idx..idx + 1);
 			self.cursor.column -= 1;
-		} else if self.cursor.row > 0 {
-			let mut later = self.buffer.split_off(self.cursor.row);
-			self.buffer[self.cursor.row-1].pop();
-			self.buffer.append(&mut later);
-			self.cursor.row -= 1;
-			self.cursor.column = self.buffer[self.cursor.row].len();
+			self.push_edit(Edit::DeleteChar { cursor_before, c });
+		} else if cursor_before.row > 0 {
+			let prev_len = self.line_len(cursor_before.row - 1);
+			let idx = self.buffer.line_to_char(cursor_before.row) - 1;
+			self.buffer.remove(idx..idx + 1);
+			self.cursor = Cursor { row: cursor_before.row - 1, column: prev_len };
+			self.push_edit(Edit::JoinLine { cursor_before, join_idx: idx });
 		}
 	}
 	// 保存
-	fn save(&self) {
+	fn save(&mut self) {
 		if let Some(path) = self.path.as_ref() {
 			if let Ok(mut file) = fs::File::create(path) {
-				for line in &self.buffer {
-					for &c in line {
+				for row in 0..self.len_lines() {
+					for c in self.line_chars(row) {
 						write!(file, "{}", c).unwrap();
 					}
 					writeln!(file).unwrap();
 				}
+				self.dirty = false;
 			}
 		}
 	}
@@ -205,6 +759,7 @@ fn main() {
 	let mut state = Kiro::default();
 
 	state.open(path::Path::new(file_path));
+	state.set_status_message(String::from("HELP: Ctrl-S = save | Ctrl-C = quit"));
 
 	let stdin = stdin();
 
@@ -212,10 +767,15 @@ fn main() {
 
 	state.draw(&mut stdout);
 
-	for evt in stdin.events() {
+	let mut events = stdin.events();
 
+	while let Some(evt) = events.next() {
+		let evt = evt.unwrap();
+		if !matches!(evt, Event::Key(Key::Ctrl('c'))) {
+			state.reset_quit_confirm();
+		}
 
-		match evt.unwrap() {
+		match evt {
 			Event::Key(Key::Char(c)) => {
 				state.insert(c);
 			},
@@ -224,21 +784,61 @@ fn main() {
 			},
 			Event::Key(Key::Up) => {
 				state.cursor_up();
+				state.commit_group();
 			},
 			Event::Key(Key::Down) => {
 				state.cursor_down();
+				state.commit_group();
 			},
 			Event::Key(Key::Left) => {
 				state.cursor_left();
+				state.commit_group();
 			},
 			Event::Key(Key::Right) => {
 				state.cursor_right();
+				state.commit_group();
+			},
+			Event::Key(Key::Alt('b')) => {
+				state.move_prev_word_start();
+				state.commit_group();
+			},
+			Event::Key(Key::Alt('f')) => {
+				state.move_next_word_start();
+				state.commit_group();
+			},
+			Event::Key(Key::Alt('e')) => {
+				state.move_next_word_end();
+				state.commit_group();
 			},
 			Event::Key(Key::Ctrl('s')) => {
 				state.save();
+				state.set_status_message(String::from("File saved"));
 			}
+			Event::Key(Key::Ctrl('z')) => {
+				state.undo();
+			},
+			Event::Key(Key::Ctrl('y')) => {
+				state.redo();
+			},
+			Event::Key(Key::Ctrl('g')) => {
+				state.toggle_marker();
+			},
+			Event::Key(Key::Ctrl('x')) => {
+				state.cut_selection();
+			},
+			Event::Key(Key::Ctrl('v')) => {
+				state.paste();
+			},
+			Event::Key(Key::Ctrl('f')) => {
+				state.search(&mut events, &mut stdout);
+			},
 			Event::Key(Key::Ctrl('c')) => {
-				return;
+				if state.marker.is_some() {
+					state.copy_selection();
+					state.set_status_message(String::from("Copied selection"));
+				} else if state.request_quit() {
+					return;
+				}
 			},
 			_ => {
 			}